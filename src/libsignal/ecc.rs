@@ -1,13 +1,107 @@
 use crate::helpers;
 use crate::libsignal::{ecc, protocol, Curve25519};
+use curve25519_dalek::constants::{ED25519_BASEPOINT_TABLE, X25519_BASEPOINT};
+use curve25519_dalek::edwards::EdwardsPoint;
+use curve25519_dalek::montgomery::MontgomeryPoint;
+use curve25519_dalek::scalar::Scalar;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use sha2::{Digest, Sha512};
 use std::convert::TryInto;
+use subtle::ConstantTimeEq;
+use zeroize::Zeroize;
+
+/// The kind of elliptic-curve key backing a public/private key pair.
+///
+/// Each variant carries its own wire type byte and serialized public-key
+/// length, so `decode_point` dispatches on the leading byte and derives its
+/// length check from `pub_len()` instead of a hardcoded magic number. Key
+/// storage (`PublicKey`/`PrivateKey`) and the copy buffer in `decode_point`
+/// are still fixed at 32 bytes, so adding a variant with a different
+/// `pub_len()` would need those widened too.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyType {
+    Djb,
+}
+
+impl KeyType {
+    /// The leading byte `decode_point` expects on the wire for this key kind.
+    /// `decode_private_point` takes bare key bytes with no type byte.
+    pub fn type_byte(&self) -> u8 {
+        match self {
+            KeyType::Djb => 0x05,
+        }
+    }
+
+    /// The length in bytes of a serialized public key of this kind, not
+    /// counting the leading type byte.
+    pub fn pub_len(&self) -> usize {
+        match self {
+            KeyType::Djb => 32,
+        }
+    }
+
+    /// Look up the `KeyType` a wire type byte identifies.
+    pub fn from_type_byte(byte: u8) -> Result<KeyType, InvalidKeyError> {
+        match byte {
+            0x05 => Ok(KeyType::Djb),
+            _ => Err(InvalidKeyError(format!("Bad key type: {}", byte))),
+        }
+    }
+}
+
+// Domain-separation prefix used by XEdDSA to keep its hash inputs distinct
+// from a plain Ed25519 signature over the same key: `2^256 - 1 - i` encoded
+// as 32 little-endian bytes.
+fn prefix(i: u8) -> [u8; 32] {
+    let mut buf = [0xffu8; 32];
+    buf[0] -= i;
+    buf
+}
+
+// Clamp the raw 32-byte X25519 scalar the same way `calculate_agreement`
+// does internally, so signing operates on the scalar that was actually used
+// to derive the Montgomery public key.
+fn clamp(mut bytes: [u8; 32]) -> Scalar {
+    bytes[0] &= 248;
+    bytes[31] &= 127;
+    bytes[31] |= 64;
+    Scalar::from_bits(bytes)
+}
 
-static DJB_TYPE: u8 = 0x05;
+// Derive the Ed25519 key pair `(A, a)` backing a Montgomery private scalar,
+// forcing `A`'s sign bit to 0 as XEdDSA requires.
+fn derive_edwards_keypair(private_key: [u8; 32]) -> (EdwardsPoint, Scalar) {
+    let mut a = clamp(private_key);
+    let mut a_point = &a * &ED25519_BASEPOINT_TABLE;
+    let a_bytes = a_point.compress().to_bytes();
+
+    if a_bytes[31] & 0x80 != 0 {
+        a = -a;
+        a_point = -a_point;
+    }
+
+    (a_point, a)
+}
 
 pub struct Curve;
 
 pub struct InvalidKeyError(pub String);
 
+impl std::fmt::Display for InvalidKeyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::fmt::Debug for InvalidKeyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "InvalidKeyError({:?})", self.0)
+    }
+}
+
+impl std::error::Error for InvalidKeyError {}
+
 impl Curve {
     pub fn generate_key_pair() -> KeyPair {
         Curve25519::generate_key_pair()
@@ -19,27 +113,24 @@ impl Curve {
         } else {
             // Truncate the number to last 8 bits
             let type_ = &bytes[offset] & 0xff;
+            let key_type = KeyType::from_type_byte(type_)?;
 
-            if type_ == DJB_TYPE.try_into().unwrap() {
-                if bytes.len() - offset < 33 {
-                    Err(InvalidKeyError(
-                        format!("Bad key length: {}", bytes.len()).to_string(),
-                    ))
-                } else {
-                    let mut key_bytes = &[0; 32][..];
-                    let start_pos = offset + 1;
-                    let result =
-                        helpers::slices::copy(&bytes, offset + 1, key_bytes, 0, key_bytes.len());
-                    match result {
-                        Ok(v) => match helpers::slices::to_array32(&(&v)) {
-                            Ok(arr) => Ok(PublicKey(arr)),
-                            Err(_) => Err(InvalidKeyError(format!("Bad key type: {}", type_))),
-                        },
+            if bytes.len() - offset < 1 + key_type.pub_len() {
+                Err(InvalidKeyError(
+                    format!("Bad key length: {}", bytes.len()).to_string(),
+                ))
+            } else {
+                let mut key_bytes = &[0; 32][..];
+                let start_pos = offset + 1;
+                let result =
+                    helpers::slices::copy(&bytes, offset + 1, key_bytes, 0, key_bytes.len());
+                match result {
+                    Ok(v) => match helpers::slices::to_array32(&(&v)) {
+                        Ok(arr) => Ok(PublicKey(arr)),
                         Err(_) => Err(InvalidKeyError(format!("Bad key type: {}", type_))),
-                    }
+                    },
+                    Err(_) => Err(InvalidKeyError(format!("Bad key type: {}", type_))),
                 }
-            } else {
-                Err(InvalidKeyError(format!("Bad key type: {}", type_)))
             }
         }
     }
@@ -57,7 +148,7 @@ impl Curve {
     ) -> Result<[u8; 32], InvalidKeyError> {
         let (a, b) = (public_key.get_type(), private_key.get_type());
 
-        if a != b || a != DJB_TYPE {
+        if a != b || a != KeyType::Djb {
             return Err(InvalidKeyError(
                 "Public and private keys must be of the same type!".to_string(),
             ));
@@ -68,6 +159,73 @@ impl Curve {
             private_key.get_private_key(),
         ))
     }
+
+    /// Sign `message` with a Montgomery (X25519) private key using XEdDSA,
+    /// so keys produced by `generate_key_pair` can also be used for
+    /// EdDSA-style signatures.
+    pub fn calculate_signature(private_key: &impl ECPrivateKey, message: &[u8]) -> [u8; 64] {
+        let (a_point, a) = derive_edwards_keypair(private_key.get_private_key());
+        let a_bytes = a_point.compress().to_bytes();
+
+        let mut z = [0u8; 64];
+        OsRng.fill_bytes(&mut z);
+
+        let mut r_hash = Sha512::new();
+        r_hash.update(&prefix(1)[..]);
+        r_hash.update(&a.to_bytes()[..]);
+        r_hash.update(message);
+        r_hash.update(&z[..]);
+        let r = Scalar::from_hash(r_hash);
+
+        let r_point = &r * &ED25519_BASEPOINT_TABLE;
+        let r_bytes = r_point.compress().to_bytes();
+
+        let mut h_hash = Sha512::new();
+        h_hash.update(&r_bytes[..]);
+        h_hash.update(&a_bytes[..]);
+        h_hash.update(message);
+        let h = Scalar::from_hash(h_hash);
+
+        let s = r + h * a;
+
+        let mut signature = [0u8; 64];
+        signature[..32].copy_from_slice(&r_bytes);
+        signature[32..].copy_from_slice(s.as_bytes());
+        signature
+    }
+
+    /// Verify an XEdDSA signature produced by `calculate_signature` against
+    /// a Montgomery (X25519) public key.
+    pub fn verify_signature(
+        public_key: &impl ECPublicKey,
+        message: &[u8],
+        signature: &[u8; 64],
+    ) -> Result<bool, InvalidKeyError> {
+        let u = public_key.get_public_key();
+        let a_point = match MontgomeryPoint(u).to_edwards(0) {
+            Some(point) => point,
+            None => return Err(InvalidKeyError("Invalid point for signature".to_string())),
+        };
+        let a_bytes = a_point.compress().to_bytes();
+
+        let r_bytes: [u8; 32] = signature[..32].try_into().unwrap();
+        let s_bytes: [u8; 32] = signature[32..].try_into().unwrap();
+
+        let s = match Scalar::from_canonical_bytes(s_bytes) {
+            Some(s) => s,
+            None => return Ok(false),
+        };
+
+        let mut h_hash = Sha512::new();
+        h_hash.update(&r_bytes[..]);
+        h_hash.update(&a_bytes[..]);
+        h_hash.update(message);
+        let h = Scalar::from_hash(h_hash);
+
+        let r_check = EdwardsPoint::vartime_double_scalar_mul_basepoint(&h, &(-a_point), &s);
+
+        Ok(r_check.compress().to_bytes() == r_bytes)
+    }
 }
 
 pub struct KeyPair {
@@ -87,32 +245,99 @@ impl KeyPair {
 pub trait ECPublicKey {
     fn from(bytes: [u8; 32]) -> Self;
     fn serialize(&self) -> [u8; 32];
-    fn get_type(&self) -> u8;
+    fn get_type(&self) -> KeyType;
     fn get_public_key(&self) -> [u8; 32];
 }
 
 pub trait ECPrivateKey {
     fn serialize(&self) -> [u8; 32];
-    fn get_type(&self) -> u8;
+    fn get_type(&self) -> KeyType;
     fn get_private_key(&self) -> [u8; 32];
 }
 
 pub struct PrivateKey(pub [u8; 32]);
 
+// Secret material: compare in constant time so that timing does not leak
+// how many leading bytes of two private scalars agree, and deliberately do
+// not derive `Ord`/`Hash`, which would otherwise invite short-circuiting
+// comparisons or key material ending up in hash-based containers/logs.
 impl PartialEq for PrivateKey {
     fn eq(&self, other: &Self) -> bool {
-        self.0 == other.0
+        self.0.ct_eq(&other.0).into()
     }
 }
 
 impl Eq for PrivateKey {}
 
+// Hand-written rather than derived so `{:?}` output (debug logs, test failure
+// messages) never shows the secret scalar. `Display` still prints it as hex.
+impl std::fmt::Debug for PrivateKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("PrivateKey").field(&"<redacted>").finish()
+    }
+}
+
+impl Drop for PrivateKey {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+// Private keys serialize as the bare 32 bytes accepted by
+// `decode_private_point` (no type byte, unlike `PublicKey`).
+impl std::str::FromStr for PrivateKey {
+    type Err = InvalidKeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes =
+            hex::decode(s).map_err(|e| InvalidKeyError(format!("Invalid hex: {}", e)))?;
+        Curve::decode_private_point(&bytes).map(|k| PrivateKey(k.get_private_key()))
+    }
+}
+
+impl std::fmt::Display for PrivateKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", hex::encode(self.0))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for PrivateKey {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&self.to_string())
+        } else {
+            serializer.serialize_bytes(&self.0)
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for PrivateKey {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let s = String::deserialize(deserializer)?;
+            s.parse().map_err(serde::de::Error::custom)
+        } else {
+            let bytes = <Vec<u8>>::deserialize(deserializer)?;
+            Curve::decode_private_point(&bytes)
+                .map(|k| PrivateKey(k.get_private_key()))
+                .map_err(|e| serde::de::Error::custom(e.0))
+        }
+    }
+}
+
 impl PrivateKey {
     pub fn new(bytes: &mut [u8; 32]) -> Self {
         let mut buf: [u8; 32] = [0; 32];
         buf.clone_from_slice(bytes);
         Self(buf)
     }
+
+    /// Recover the Montgomery public key matching this private scalar.
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey::from_private_key(self)
+    }
 }
 
 impl ECPrivateKey for PrivateKey {
@@ -120,8 +345,8 @@ impl ECPrivateKey for PrivateKey {
         self.0
     }
 
-    fn get_type(&self) -> u8 {
-        DJB_TYPE
+    fn get_type(&self) -> KeyType {
+        KeyType::Djb
     }
 
     fn get_private_key(&self) -> [u8; 32] {
@@ -129,12 +354,23 @@ impl ECPrivateKey for PrivateKey {
     }
 }
 
+#[derive(Debug)]
 pub struct PublicKey(pub [u8; 32]);
 
 impl PublicKey {
     pub fn new(bytes: [u8; 32]) -> Self {
         PublicKey(bytes)
     }
+
+    /// Derive the Montgomery public key matching `private`, multiplying the
+    /// clamped X25519 scalar by the Curve25519 base point. Lets callers
+    /// reconstruct a full `KeyPair` from stored private material without
+    /// retaining the public key separately.
+    pub fn from_private_key(private: &impl ECPrivateKey) -> PublicKey {
+        let scalar = clamp(private.get_private_key());
+        let public = (scalar * X25519_BASEPOINT).to_bytes();
+        PublicKey(public)
+    }
 }
 
 impl PartialEq for PublicKey {
@@ -154,8 +390,8 @@ impl ECPublicKey for PublicKey {
         self.0
     }
 
-    fn get_type(&self) -> u8 {
-        DJB_TYPE
+    fn get_type(&self) -> KeyType {
+        KeyType::Djb
     }
 
     fn get_public_key(&self) -> [u8; 32] {
@@ -163,6 +399,57 @@ impl ECPublicKey for PublicKey {
     }
 }
 
+// Public keys serialize with the leading type byte so they round-trip
+// through `decode_point`.
+impl std::str::FromStr for PublicKey {
+    type Err = InvalidKeyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes =
+            hex::decode(s).map_err(|e| InvalidKeyError(format!("Invalid hex: {}", e)))?;
+        let decoded = Curve::decode_point(&bytes, 0)?;
+        Ok(PublicKey(decoded.get_public_key()))
+    }
+}
+
+impl std::fmt::Display for PublicKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut bytes = Vec::with_capacity(1 + self.get_type().pub_len());
+        bytes.push(self.get_type().type_byte());
+        bytes.extend_from_slice(&self.0);
+        write!(f, "{}", hex::encode(bytes))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for PublicKey {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&self.to_string())
+        } else {
+            let mut bytes = Vec::with_capacity(1 + self.get_type().pub_len());
+            bytes.push(self.get_type().type_byte());
+            bytes.extend_from_slice(&self.0);
+            serializer.serialize_bytes(&bytes)
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for PublicKey {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            let s = String::deserialize(deserializer)?;
+            s.parse().map_err(serde::de::Error::custom)
+        } else {
+            let bytes = <Vec<u8>>::deserialize(deserializer)?;
+            Curve::decode_point(&bytes, 0)
+                .map(|k| PublicKey(k.get_public_key()))
+                .map_err(|e| serde::de::Error::custom(e.0))
+        }
+    }
+}
+
 #[cfg(test)]
 pub mod tests {
 
@@ -233,4 +520,89 @@ pub mod tests {
         }
         panic!("Expected Ok, got Error");
     }
+
+    #[test]
+    pub fn test_decode_point_rejects_unknown_key_type() {
+        let b = &helpers::slices::concat_2(&[0x00, 0x08, 0x07], &[0x00; 64][..]);
+
+        match Curve::decode_point(b, 2) {
+            Ok(_) => panic!("Expected Error"),
+            Err(InvalidKeyError(s)) => assert_eq!(s, "Bad key type: 7".to_string()),
+        }
+    }
+
+    #[test]
+    pub fn test_public_key_hex_round_trip() {
+        let pair = Curve::generate_key_pair();
+
+        let encoded = pair.public_key.to_string();
+        let decoded: PublicKey = encoded.parse().expect("valid hex public key");
+
+        assert_eq!(decoded, pair.public_key);
+    }
+
+    #[test]
+    pub fn test_private_key_hex_round_trip() {
+        let pair = Curve::generate_key_pair();
+
+        let encoded = pair.private_key.to_string();
+        let decoded: PrivateKey = encoded.parse().expect("valid hex private key");
+
+        assert_eq!(decoded, pair.private_key);
+    }
+
+    #[test]
+    pub fn test_public_key_from_str_rejects_bad_hex() {
+        match "not hex".parse::<PublicKey>() {
+            Ok(_) => panic!("Expected Error"),
+            Err(_) => assert!(true),
+        }
+    }
+
+    #[test]
+    pub fn test_private_key_eq_distinguishes_keys() {
+        let a = PrivateKey::new(&mut [0x01; 32]);
+        let b = PrivateKey::new(&mut [0x01; 32]);
+        let c = PrivateKey::new(&mut [0x02; 32]);
+
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    pub fn test_public_key_from_private_key() {
+        let pair = Curve::generate_key_pair();
+
+        let derived = PublicKey::from_private_key(&pair.private_key);
+
+        assert_eq!(derived, pair.public_key);
+        assert_eq!(pair.private_key.public_key(), pair.public_key);
+    }
+
+    #[test]
+    pub fn test_sign_and_verify_signature() {
+        let alice = Curve::generate_key_pair();
+        let message = b"a message that needs authenticating";
+
+        let signature = Curve::calculate_signature(&alice.private_key, message);
+
+        match Curve::verify_signature(&alice.public_key, message, &signature) {
+            Ok(valid) => assert!(valid),
+            Err(_) => panic!("Expected Ok, got Error"),
+        }
+    }
+
+    #[test]
+    pub fn test_verify_signature_rejects_tampered_message() {
+        let alice = Curve::generate_key_pair();
+        let message = b"a message that needs authenticating";
+        let tampered = b"a message that needs authenticatinG";
+
+        let signature = Curve::calculate_signature(&alice.private_key, message);
+
+        match Curve::verify_signature(&alice.public_key, tampered, &signature) {
+            Ok(valid) => assert!(!valid),
+            Err(_) => panic!("Expected Ok, got Error"),
+        }
+    }
 }